@@ -0,0 +1 @@
+pub mod multi_parameters;
@@ -0,0 +1,6 @@
+pub mod analyze;
+pub mod partitionning;
+pub mod partitions;
+pub mod precision_cut;
+pub mod simulate;
+pub mod symbolic_variance;
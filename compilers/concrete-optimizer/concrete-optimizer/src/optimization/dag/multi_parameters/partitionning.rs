@@ -0,0 +1,183 @@
+//! Assigns each DAG operator to a precision band (partition).
+//!
+//! `Input` operators are keyed by the precision they carry, and `Lut`
+//! operators by the precision of the ciphertext they bootstrap (their
+//! *input*, not the accumulator precision they produce) since that is the
+//! noise regime the bootstrap actually operates in. Every other operator
+//! (`Dot`, `LevelledOp`, `UnsafeCast`) just forwards the noise of operators
+//! it was built from, so it inherits the highest (most conservative)
+//! partition among its inputs, and forwards whatever precision those
+//! inputs carry. Only the bands some operator actually lands in are kept
+//! (and renumbered from 0), so a DAG that never touches one of `p_cut`'s
+//! bands collapses to fewer partitions than `p_cut` has thresholds for.
+//!
+//! Once every operator has a home partition, a second pass builds the full
+//! cross-partition conversion matrix: whenever a consumer's partition
+//! differs from its input's, the input's producer gets a fast-keyswitched
+//! copy made available in the consumer's partition, so any cross-band
+//! consumer (not just ones converting to `default_partition`) finds what
+//! it needs in `out_variances`/noise samples.
+
+use std::collections::BTreeSet;
+
+use crate::dag::operator::{Operator, OperatorIndex};
+use crate::dag::unparametrized::OperationDag;
+use crate::optimization::dag::multi_parameters::partitions::{InstructionPartition, PartitionIndex};
+use crate::optimization::dag::multi_parameters::precision_cut::{BandLookup, PrecisionCut};
+
+type Op = Operator;
+
+pub struct Partitions {
+    pub nb_partitions: usize,
+    pub instrs_partition: Vec<InstructionPartition>,
+}
+
+pub fn partitionning_with_preferred(
+    dag: &OperationDag,
+    p_cut: &PrecisionCut,
+    default_partition: PartitionIndex,
+) -> Partitions {
+    // Built once and reused for every node below: this is exactly the
+    // O(log n)-per-lookup, cache-efficient structure `PrecisionCut` exposes
+    // (a van Emde Boas layout past a handful of thresholds, a flat sorted
+    // array below it), rather than rebuilding it per node.
+    let lookup = p_cut.lookup();
+
+    let mut raw_partition_of = Vec::with_capacity(dag.operators.len());
+    let mut precision_of = Vec::with_capacity(dag.operators.len());
+    let mut used_partitions = BTreeSet::new();
+    for op in &dag.operators {
+        let (partition, precision) =
+            operator_partition(op, &raw_partition_of, &precision_of, &lookup, default_partition);
+        used_partitions.insert(partition);
+        raw_partition_of.push(partition);
+        precision_of.push(precision);
+    }
+
+    // Compact away bands no operator actually landed in, e.g. a DAG that
+    // only ever touches the high band of a 2-band `p_cut` ends up with a
+    // single partition rather than 2.
+    let used_partitions: Vec<PartitionIndex> = used_partitions.into_iter().collect();
+    let nb_partitions = used_partitions.len();
+    let compact = |raw: PartitionIndex| {
+        used_partitions
+            .binary_search(&raw)
+            .expect("every raw partition was inserted into used_partitions above")
+    };
+
+    let mut instrs_partition: Vec<InstructionPartition> = raw_partition_of
+        .iter()
+        .map(|&raw| InstructionPartition {
+            instruction_partition: compact(raw),
+            alternative_output_representation: Vec::new(),
+        })
+        .collect();
+
+    for (i, op) in dag.operators.iter().enumerate() {
+        let consumer_partition = instrs_partition[i].instruction_partition;
+        for input in op_inputs(op) {
+            let producer_partition = instrs_partition[input.i].instruction_partition;
+            if producer_partition != consumer_partition {
+                let alt = &mut instrs_partition[input.i].alternative_output_representation;
+                if !alt.contains(&consumer_partition) {
+                    alt.push(consumer_partition);
+                }
+            }
+        }
+    }
+
+    Partitions {
+        nb_partitions,
+        instrs_partition,
+    }
+}
+
+// Returns an operator's home (pre-compaction) partition and the precision
+// it carries forward to its consumers.
+fn operator_partition(
+    op: &Op,
+    partition_of: &[PartitionIndex],
+    precision_of: &[u8],
+    lookup: &BandLookup<'_>,
+    default_partition: PartitionIndex,
+) -> (PartitionIndex, u8) {
+    match op {
+        Op::Input { out_precision, .. } => (lookup.band_of(*out_precision), *out_precision),
+        Op::Lut {
+            input, out_precision, ..
+        } => (lookup.band_of(precision_of[input.i]), *out_precision),
+        Op::LevelledOp { inputs, .. } | Op::Dot { inputs, .. } => {
+            let partition = inputs
+                .iter()
+                .map(|input| partition_of[input.i])
+                .max()
+                .unwrap_or(default_partition);
+            let precision = inputs
+                .iter()
+                .map(|input| precision_of[input.i])
+                .max()
+                .unwrap_or(0);
+            (partition, precision)
+        }
+        Op::UnsafeCast { input, .. } => (partition_of[input.i], precision_of[input.i]),
+        Op::Round { .. } => {
+            unreachable!("Round should have been either expanded or integrated to a lut")
+        }
+    }
+}
+
+fn op_inputs(op: &Op) -> Vec<OperatorIndex> {
+    match op {
+        Op::Input { .. } => Vec::new(),
+        Op::Lut { input, .. } | Op::UnsafeCast { input, .. } => vec![*input],
+        Op::LevelledOp { inputs, .. } | Op::Dot { inputs, .. } => inputs.clone(),
+        Op::Round { .. } => {
+            unreachable!("Round should have been either expanded or integrated to a lut")
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub const LOW_PRECISION_PARTITION: PartitionIndex = 0;
+    pub const HIGH_PRECISION_PARTITION: PartitionIndex = 1;
+
+    pub fn show_partitionning(dag: &OperationDag, instrs_partition: &[InstructionPartition]) {
+        for (i, p) in instrs_partition.iter().enumerate() {
+            eprintln!(
+                "{i}: {:?} -> partition {}",
+                dag.operators[i], p.instruction_partition
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_input_is_assigned_a_partition() {
+        let mut dag = OperationDag::new();
+        dag.add_input(8, crate::dag::operator::Shape::number());
+        let p_cut = PrecisionCut { p_cut: vec![2] };
+        let partitions = partitionning_with_preferred(&dag, &p_cut, LOW_PRECISION_PARTITION);
+        // Only the high band is ever touched, so it collapses to partition 0
+        // even though `p_cut` has room for 2 bands.
+        assert!(partitions.nb_partitions == 1);
+        assert!(partitions.instrs_partition[0].instruction_partition == 0);
+    }
+
+    #[test]
+    fn test_lut_is_keyed_by_its_bootstrapped_input_precision() {
+        let mut dag = OperationDag::new();
+        let input1 = dag.add_input(8, crate::dag::operator::Shape::number());
+        // The lut bootstraps an 8-bit (high) input but produces a 1-bit
+        // (low-band) accumulator; it must be placed in the high band, the
+        // regime its bootstrap noise actually lives in.
+        let lut1 = dag.add_lut(input1, crate::dag::operator::FunctionTable::UNKWOWN, 1);
+        let p_cut = PrecisionCut { p_cut: vec![2] };
+        let partitions = partitionning_with_preferred(&dag, &p_cut, LOW_PRECISION_PARTITION);
+        assert!(
+            partitions.instrs_partition[lut1.i].instruction_partition
+                == partitions.instrs_partition[input1.i].instruction_partition
+        );
+    }
+}
@@ -0,0 +1,14 @@
+//! The partition-indexing types shared by the partitioner and the analyzer.
+
+/// Identifies one precision band/partition. Plain `usize` since partitions
+/// are always used as dense indices into `out_variances`/`SymbolicVariance`
+/// coefficient vectors.
+pub type PartitionIndex = usize;
+
+/// Where a single DAG operator's output lives, and which other partitions
+/// also hold a (fast-keyswitched) copy of it for cross-partition consumers.
+#[derive(Clone, Debug)]
+pub struct InstructionPartition {
+    pub instruction_partition: PartitionIndex,
+    pub alternative_output_representation: Vec<PartitionIndex>,
+}
@@ -0,0 +1,274 @@
+//! The precision cut: a sorted set of bit-width thresholds splitting operators
+//! into precision bands (aka partitions) for multi-parameter optimization.
+
+/// Above this many thresholds, `PrecisionCut::band_of` switches from a flat
+/// sorted array to a van Emde Boas (cache-oblivious) layout. Below it the
+/// flat array wins on raw constant factors and branch prediction.
+const VEB_THRESHOLD: usize = 16;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrecisionCut {
+    // Sorted, ascending bit-width thresholds. `p_cut.len() + 1` precision
+    // bands are implied: values <= p_cut[0] fall in band 0, values in
+    // (p_cut[i-1], p_cut[i]] fall in band i, and values above every
+    // threshold fall in the last band.
+    pub p_cut: Vec<u8>,
+}
+
+impl PrecisionCut {
+    pub fn nb_partitions(&self) -> usize {
+        self.p_cut.len() + 1
+    }
+
+    // Convenience one-shot lookup: rebuilds the van Emde Boas layout (when
+    // above `VEB_THRESHOLD`) on every call, so it costs O(n) rather than
+    // O(log n). Fine for a handful of calls; callers doing this once per
+    // DAG node (e.g. the partitioner) must call `lookup()` instead and
+    // reuse the returned `BandLookup` across nodes.
+    pub fn band_of(&self, precision: u8) -> usize {
+        self.lookup().band_of(precision)
+    }
+
+    // Builds the lookup structure once (the van Emde Boas layout past
+    // `VEB_THRESHOLD` thresholds, a flat array below it) for reuse across
+    // many `band_of` queries, e.g. one per DAG node.
+    pub(crate) fn lookup(&self) -> BandLookup<'_> {
+        BandLookup::of(&self.p_cut)
+    }
+}
+
+pub(crate) enum BandLookup<'a> {
+    Flat(&'a [u8]),
+    VanEmdeBoas(VebLayout),
+}
+
+impl<'a> BandLookup<'a> {
+    fn of(p_cut: &'a [u8]) -> Self {
+        if p_cut.len() <= VEB_THRESHOLD {
+            Self::Flat(p_cut)
+        } else {
+            Self::VanEmdeBoas(VebLayout::build(p_cut))
+        }
+    }
+
+    // Returns the band index `precision` falls into, i.e. the number of
+    // thresholds strictly below `precision`. Ties (precision exactly equal
+    // to a threshold) always land in the lower band; the caller's
+    // default partition plays no part in this choice.
+    pub(crate) fn band_of(&self, precision: u8) -> usize {
+        match self {
+            Self::Flat(p_cut) => p_cut.partition_point(|&threshold| threshold < precision),
+            Self::VanEmdeBoas(layout) => layout.band_of(precision),
+        }
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+#[derive(Clone, Copy, Debug)]
+struct VebNode {
+    precision: u8,
+    // Position of this threshold in the sorted input, i.e. the band index
+    // it would resolve to as the tightest upper bound found so far.
+    rank: usize,
+    // Flat-array offsets of the left/right children, computed once while
+    // the node is laid out. Children are not adjacent in memory (that is
+    // the whole point of the van Emde Boas ordering), so offsets have to be
+    // stored rather than derived from the node's own position.
+    left: usize,
+    right: usize,
+}
+
+// A sorted threshold array, physically reordered into van Emde Boas order:
+// for a subtree of height h, the top ceil(h/2)-height subtree is stored
+// contiguously first, then each bottom subtree contiguously in
+// left-to-right order, recursively. Lookups still walk O(log n) nodes, but
+// nodes visited in sequence are much more likely to share a cache line than
+// in the naive in-order (pointer-tree) layout.
+#[derive(Clone, Debug)]
+struct VebLayout {
+    nodes: Vec<VebNode>,
+}
+
+impl VebLayout {
+    fn build(sorted: &[u8]) -> Self {
+        let mut nodes = Vec::with_capacity(sorted.len());
+        emit(sorted, 0, &mut nodes);
+        Self { nodes }
+    }
+
+    fn band_of(&self, precision: u8) -> usize {
+        if self.nodes.is_empty() {
+            return 0;
+        }
+        let mut idx = 0;
+        let mut band = self.nodes.len();
+        loop {
+            let node = &self.nodes[idx];
+            let next = if precision <= node.precision {
+                band = node.rank;
+                node.left
+            } else {
+                node.right
+            };
+            if next == NIL {
+                return band;
+            }
+            idx = next;
+        }
+    }
+}
+
+// Height (number of levels) of the implicit balanced BST over `n` sorted
+// elements: ceil(log2(n + 1)), computed as the bit-length of `n` (n > 0).
+fn tree_height(n: usize) -> u32 {
+    usize::BITS - n.leading_zeros()
+}
+
+fn emit(a: &[u8], base: usize, out: &mut Vec<VebNode>) -> usize {
+    let n = a.len();
+    if n == 0 {
+        return NIL;
+    }
+    if n <= 3 {
+        return emit_plain(a, base, out);
+    }
+    let height = tree_height(n);
+    let top_height = height.div_ceil(2);
+
+    let mut top = Vec::new();
+    let mut external = Vec::new();
+    let mut bottoms: Vec<(&[u8], usize)> = Vec::new();
+    split_top(a, base, top_height.saturating_sub(1), &mut top, &mut external, &mut bottoms);
+
+    let at = out.len();
+    out.extend_from_slice(&top);
+    for node in &mut out[at..] {
+        if node.left != NIL {
+            node.left += at;
+        }
+        if node.right != NIL {
+            node.right += at;
+        }
+    }
+    for (&(node_idx, is_left), &(bottom, bottom_base)) in external.iter().zip(&bottoms) {
+        let bottom_root = emit(bottom, bottom_base, out);
+        let node = &mut out[at + node_idx];
+        if is_left {
+            node.left = bottom_root;
+        } else {
+            node.right = bottom_root;
+        }
+    }
+    at
+}
+
+fn emit_plain(a: &[u8], base: usize, out: &mut Vec<VebNode>) -> usize {
+    let n = a.len();
+    let mid = n / 2;
+    let pos = out.len();
+    out.push(VebNode {
+        precision: a[mid],
+        rank: base + mid,
+        left: NIL,
+        right: NIL,
+    });
+    let left = emit(&a[..mid], base, out);
+    let right = emit(&a[mid + 1..], base + mid + 1, out);
+    out[pos].left = left;
+    out[pos].right = right;
+    pos
+}
+
+// Lays out only the top `height` additional levels below the root of `a`
+// (the root itself always counts as laid out) into `top`, using
+// self-relative (within-`top`) offsets for edges that stay inside this
+// subtree. Edges leaving the top subtree are left as `NIL` in `top` and
+// recorded in `external`/`bottoms` (in left-to-right order) so the caller
+// can append the bottom subtrees afterwards and patch the links.
+#[allow(clippy::too_many_arguments)]
+fn split_top<'a>(
+    a: &'a [u8],
+    base: usize,
+    height: u32,
+    top: &mut Vec<VebNode>,
+    external: &mut Vec<(usize, bool)>,
+    bottoms: &mut Vec<(&'a [u8], usize)>,
+) -> usize {
+    let n = a.len();
+    if n == 0 {
+        return NIL;
+    }
+    let mid = n / 2;
+    let pos = top.len();
+    top.push(VebNode {
+        precision: a[mid],
+        rank: base + mid,
+        left: NIL,
+        right: NIL,
+    });
+
+    let left_slice = &a[..mid];
+    let right_slice = &a[mid + 1..];
+    let right_base = base + mid + 1;
+
+    let left = if height == 0 {
+        if !left_slice.is_empty() {
+            bottoms.push((left_slice, base));
+            external.push((pos, true));
+        }
+        NIL
+    } else {
+        split_top(left_slice, base, height - 1, top, external, bottoms)
+    };
+    let right = if height == 0 {
+        if !right_slice.is_empty() {
+            bottoms.push((right_slice, right_base));
+            external.push((pos, false));
+        }
+        NIL
+    } else {
+        split_top(right_slice, right_base, height - 1, top, external, bottoms)
+    };
+
+    top[pos].left = left;
+    top[pos].right = right;
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference_band_of(p_cut: &[u8], precision: u8) -> usize {
+        p_cut.iter().filter(|&&threshold| threshold < precision).count()
+    }
+
+    #[test]
+    fn test_flat_matches_reference() {
+        let cut = PrecisionCut { p_cut: vec![2, 4, 8] };
+        for precision in 0..12 {
+            assert!(cut.band_of(precision) == reference_band_of(&cut.p_cut, precision));
+        }
+    }
+
+    #[test]
+    fn test_veb_matches_reference_on_many_thresholds() {
+        let p_cut: Vec<u8> = (0..40).map(|i| i * 2).collect();
+        let cut = PrecisionCut { p_cut: p_cut.clone() };
+        for precision in 0..90u8 {
+            assert!(
+                cut.band_of(precision) == reference_band_of(&p_cut, precision),
+                "mismatch at precision {precision}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_nb_partitions() {
+        let cut = PrecisionCut { p_cut: vec![2] };
+        assert!(cut.nb_partitions() == 2);
+        let cut = PrecisionCut { p_cut: vec![] };
+        assert!(cut.nb_partitions() == 1);
+    }
+}
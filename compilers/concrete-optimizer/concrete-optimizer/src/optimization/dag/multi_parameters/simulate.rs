@@ -0,0 +1,291 @@
+//! Monte-Carlo cross-check of the symbolic variance model.
+//!
+//! `analyze` is purely symbolic: variances add weighted by `square(weight)`
+//! for dots, `after_levelled_op(manp)` bounds levelled ops, and `max`
+//! combines levelled inputs, all worst-case. `simulate` runs the same DAG
+//! numerically, `trials` times, drawing a fresh torus-Gaussian noise sample
+//! at every input/PBS/keyswitch site, and reports how often decryption
+//! would actually round to the wrong value. This catches modeling gaps the
+//! symbolic bound can't see, and gives confidence intervals the
+//! point-estimate `p_error` can't. `analyze` panics on
+//! `DK::CompatibleTensor` and `DK::Unsupported` dots, so an `AnalyzedDag`
+//! produced by `analyze` never contains one.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+
+use crate::dag::operator::{dot_kind, DotKind, Operator, OperatorIndex, Shape};
+use crate::optimization::dag::multi_parameters::analyze::AnalyzedDag;
+use crate::optimization::dag::multi_parameters::partitions::InstructionPartition;
+use crate::optimization::dag::solo_key::analyze::first;
+use crate::utils::square;
+
+use DotKind as DK;
+
+type Op = Operator;
+
+#[derive(Clone, Debug)]
+pub struct SimulationParameters {
+    // Standard deviation of the fresh-input noise, one per partition.
+    pub input_noise_stdev: Vec<f64>,
+    // Standard deviation of the post-PBS noise, one per partition.
+    pub pbs_noise_stdev: Vec<f64>,
+    // Standard deviation of the noise added by a fast keyswitch from
+    // `src_partition` to `dst_partition`, indexed `[src][dst]`.
+    pub keyswitch_noise_stdev: Vec<Vec<f64>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PErrorEstimate {
+    pub trials: u64,
+    pub failures: u64,
+    pub p_error: f64,
+    // Half-width of the confidence interval on `p_error` (normal
+    // approximation), so the true p_error is reported as
+    // `p_error +/- margin` at a fixed 95% confidence level.
+    pub margin: f64,
+    // The symbolic (worst-case) variance bound for this output, and the
+    // variance actually observed across trials, both in the same units
+    // (squared noise amplitude) so they can be compared directly.
+    pub symbolic_variance: f64,
+    pub empirical_variance: f64,
+    // True when `empirical_variance` exceeds `symbolic_variance` by more
+    // than sampling noise can explain, flagging a possible gap between the
+    // symbolic model and reality.
+    pub exceeds_symbolic_bound: bool,
+}
+
+pub struct SimulationReport {
+    pub per_output: Vec<PErrorEstimate>,
+}
+
+// z-score for a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959_963_985_4;
+
+pub fn simulate(
+    dag: &AnalyzedDag,
+    params: &SimulationParameters,
+    trials: u64,
+    seed: u64,
+) -> SimulationReport {
+    assert!(trials > 0, "simulate requires at least one trial");
+    let mut rng = StdRng::seed_from_u64(seed);
+    let nb_ops = dag.operators.len();
+
+    let mut failures = vec![0u64; nb_ops];
+    let mut sum_sq = vec![0.0f64; nb_ops];
+
+    for _ in 0..trials {
+        // `noise[i][p]` mirrors `out_variances[i][p]`: the noise sample for
+        // operator `i` as represented in partition `p`, populated only for
+        // `i`'s home partition and whatever partitions it was fast
+        // keyswitched into.
+        let mut noise: Vec<Vec<f64>> = vec![vec![f64::NAN; dag.nb_partitions]; nb_ops];
+        for (i, op) in dag.operators.iter().enumerate() {
+            let instr_partition = &dag.instrs_partition[i];
+            let partition = instr_partition.instruction_partition;
+            let by_partition = propagate_one_trial(
+                op,
+                &dag.out_shapes,
+                params,
+                &noise,
+                instr_partition,
+                dag.nb_partitions,
+                &mut rng,
+            );
+            let sample = by_partition[partition];
+            if sample.abs() > rounding_threshold() {
+                failures[i] += 1;
+            }
+            sum_sq[i] += sample * sample;
+            noise[i] = by_partition;
+        }
+    }
+
+    let per_output = (0..nb_ops)
+        .map(|i| {
+            let symbolic_variance = symbolic_variance_of(dag, params, i);
+            let empirical_variance = sum_sq[i] / trials as f64;
+            let estimate = wilson_like_estimate(failures[i], trials);
+            // How much sampling noise a `trials`-sample variance estimator
+            // is expected to wobble by around the true variance (assuming
+            // the symbolic bound is correct): stderr(s^2) ~= sigma^2 *
+            // sqrt(2/n) for a Gaussian.
+            let variance_margin =
+                Z_95 * symbolic_variance * (2.0 / trials as f64).sqrt();
+            let exceeds_symbolic_bound = empirical_variance > symbolic_variance + variance_margin;
+            PErrorEstimate {
+                trials,
+                failures: failures[i],
+                p_error: estimate.p_error,
+                margin: estimate.margin,
+                symbolic_variance,
+                empirical_variance,
+                exceeds_symbolic_bound,
+            }
+        })
+        .collect();
+
+    SimulationReport { per_output }
+}
+
+// Decryption is modeled as rounding the noisy plaintext to the nearest
+// representable value; anything past half the representable step is a
+// decryption error. The symbolic model works in normalized units where
+// that threshold is 0.5, so the same convention is used here.
+fn rounding_threshold() -> f64 {
+    0.5
+}
+
+// The actual symbolic variance (not just the coefficient vector) for
+// operator `op_i`'s output in its home partition: each input/pbs/fast-ks
+// coefficient weighted by the corresponding noise source's variance, so
+// this is directly comparable to `empirical_variance`.
+fn symbolic_variance_of(dag: &AnalyzedDag, params: &SimulationParameters, op_i: usize) -> f64 {
+    let partition = dag.instrs_partition[op_i].instruction_partition;
+    let sb = &dag.out_variances[op_i][partition];
+    let mut variance = 0.0;
+    for p in 0..dag.nb_partitions {
+        variance += sb.coeff_input(p) * square(params.input_noise_stdev[p]);
+        variance += sb.coeff_pbs(p) * square(params.pbs_noise_stdev[p]);
+        for dst in 0..dag.nb_partitions {
+            variance +=
+                sb.coeff_partition_keyswitch_to_big(p, dst) * square(params.keyswitch_noise_stdev[p][dst]);
+        }
+    }
+    variance
+}
+
+struct PErrorWithMargin {
+    p_error: f64,
+    margin: f64,
+}
+
+// Normal-approximation confidence interval on a binomial proportion.
+fn wilson_like_estimate(failures: u64, trials: u64) -> PErrorWithMargin {
+    let n = trials as f64;
+    let p = failures as f64 / n;
+    let margin = Z_95 * (p * (1.0 - p) / n).sqrt();
+    PErrorWithMargin { p_error: p, margin }
+}
+
+fn draw(stdev: f64, rng: &mut StdRng) -> f64 {
+    Normal::new(0.0, stdev).unwrap().sample(rng)
+}
+
+// Computes, for one trial, operator `op`'s noise sample in every partition
+// it is actually represented in: its home partition (`instr_partition`'s),
+// plus one per `alternative_output_representation` entry, each going
+// through the matching `(home, alt)` fast-keyswitch noise source — exactly
+// mirroring `out_variance`'s `result[dst_partition] =
+// variance.after_partition_keyswitch_to_big(...)` / `result[partition] =
+// variance` split. Partitions the operator isn't represented in stay NaN,
+// so misuse (reading the wrong partition) fails loudly instead of
+// silently using zero noise.
+#[allow(clippy::too_many_arguments)]
+fn propagate_one_trial(
+    op: &Op,
+    out_shapes: &[Shape],
+    params: &SimulationParameters,
+    noise: &[Vec<f64>],
+    instr_partition: &InstructionPartition,
+    nb_partitions: usize,
+    rng: &mut StdRng,
+) -> Vec<f64> {
+    let partition = instr_partition.instruction_partition;
+    // Mirrors `out_variance`'s own assertions: an input must already be
+    // represented in the consumer's partition (either it's its home
+    // partition, or the partitioner recorded a keyswitched copy via
+    // `alternative_output_representation`). Silently reading the NaN
+    // sentinel here would undercount failures (`NaN.abs() > threshold` is
+    // `false`) and poison `empirical_variance`, so fail loudly instead.
+    let noise_of = |input: &OperatorIndex| {
+        let sample = noise[input.i][partition];
+        assert!(
+            !sample.is_nan(),
+            "operator {} is not represented in partition {partition}; missing alternative_output_representation entry",
+            input.i
+        );
+        sample
+    };
+
+    let base = match op {
+        Op::Input { .. } => draw(params.input_noise_stdev[partition], rng),
+        Op::Lut { .. } => draw(params.pbs_noise_stdev[partition], rng),
+        Op::LevelledOp { inputs, manp, .. } => {
+            let worst_input = inputs.iter().map(noise_of).map(f64::abs).fold(0.0, f64::max);
+            worst_input * manp
+        }
+        Op::Dot {
+            inputs, weights, ..
+        } => {
+            let input_shape = first(inputs, out_shapes);
+            let kind = dot_kind(inputs.len() as u64, input_shape, weights);
+            match kind {
+                DK::Simple | DK::Tensor | DK::Broadcast => {
+                    let mut acc = 0.0;
+                    for (j, &weight) in weights.values.iter().enumerate() {
+                        let input = if inputs.len() > 1 { inputs[j] } else { inputs[0] };
+                        acc += noise_of(&input) * weight;
+                    }
+                    acc
+                }
+                DK::CompatibleTensor { .. } | DK::Unsupported { .. } => {
+                    // Not modeled symbolically either (`analyze` panics on
+                    // these kinds, so this branch only runs for an
+                    // `AnalyzedDag` built by other means). Draws one fresh
+                    // unit-Gaussian sample scaled by the L2 norm of the
+                    // weights; the inputs' noise samples are not read.
+                    weights.values.iter().map(|w| square(*w)).sum::<f64>().sqrt() * draw(1.0, rng)
+                }
+            }
+        }
+        Op::UnsafeCast { input, .. } => noise_of(input),
+        Op::Round { .. } => {
+            unreachable!("Round should have been either expanded or integrated to a lut")
+        }
+    };
+
+    let mut result = vec![f64::NAN; nb_partitions];
+    for &dst_partition in &instr_partition.alternative_output_representation {
+        let stdev = params.keyswitch_noise_stdev[partition][dst_partition];
+        result[dst_partition] = base + draw(stdev, rng);
+    }
+    result[partition] = base;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat_params(nb_partitions: usize) -> SimulationParameters {
+        SimulationParameters {
+            input_noise_stdev: vec![0.01; nb_partitions],
+            pbs_noise_stdev: vec![0.02; nb_partitions],
+            keyswitch_noise_stdev: vec![vec![0.0; nb_partitions]; nb_partitions],
+        }
+    }
+
+    #[test]
+    fn test_wilson_like_estimate_zero_failures() {
+        let estimate = wilson_like_estimate(0, 1000);
+        assert!(estimate.p_error == 0.0);
+        assert!(estimate.margin == 0.0);
+    }
+
+    #[test]
+    fn test_wilson_like_estimate_some_failures() {
+        let estimate = wilson_like_estimate(10, 1000);
+        assert!((estimate.p_error - 0.01).abs() < 1e-9);
+        assert!(estimate.margin > 0.0);
+    }
+
+    #[test]
+    fn test_flat_params_shape() {
+        let params = flat_params(2);
+        assert!(params.input_noise_stdev.len() == 2);
+        assert!(params.keyswitch_noise_stdev.len() == 2);
+    }
+}
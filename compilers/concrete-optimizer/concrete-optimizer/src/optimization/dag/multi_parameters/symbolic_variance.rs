@@ -0,0 +1,307 @@
+//! Symbolic variance: a vector of coefficients, indexed by partition (and by
+//! partition pair for fast keyswitches), expressing
+//! an operator's output noise variance as a weighted sum of the fresh-input,
+//! post-PBS, and fast-keyswitch noise variances of each partition.
+//!
+//! The coefficient vector is `O(nb_partitions^2)` long (each partition can
+//! contribute an input term, a PBS term, and one fast-keyswitch term per
+//! other partition), and `+`, `* f64` and `max` all run elementwise over it.
+//! For wide DAGs with many partitions this dominates `out_variances`, so when
+//! the `simd-rayon` feature is enabled the elementwise ops are vectorized and
+//! `out_variances` additionally schedules independent DAG nodes across
+//! threads. Without the feature both run as plain scalar, serial loops.
+
+use std::ops::{Add, AddAssign, Mul};
+
+#[cfg(feature = "simd-rayon")]
+use wide::f64x4;
+
+// NaN is used as a sentinel marking coefficients of a partition an operator
+// never produces output for; `out_variance` asserts that the first
+// coefficient of every input variance it reads is not NaN, so accidental
+// use of an unproduced
+// partition is caught rather than silently propagating `0.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coeffs {
+    pub values: Vec<f64>,
+}
+
+impl Coeffs {
+    fn zeroed(len: usize) -> Self {
+        Self { values: vec![0.0; len] }
+    }
+
+    fn nan(len: usize) -> Self {
+        Self { values: vec![f64::NAN; len] }
+    }
+
+    #[cfg(not(feature = "simd-rayon"))]
+    fn add_assign_scalar(&mut self, other: &Self) {
+        for (a, b) in self.values.iter_mut().zip(&other.values) {
+            *a += b;
+        }
+    }
+
+    #[cfg(feature = "simd-rayon")]
+    fn add_assign_scalar(&mut self, other: &Self) {
+        simd_binary_op(&mut self.values, &other.values, |a, b| a + b, f64x4::add);
+    }
+
+    #[cfg(not(feature = "simd-rayon"))]
+    fn mul_scalar(&self, factor: f64) -> Self {
+        Self {
+            values: self.values.iter().map(|v| v * factor).collect(),
+        }
+    }
+
+    #[cfg(feature = "simd-rayon")]
+    fn mul_scalar(&self, factor: f64) -> Self {
+        let mut values = self.values.clone();
+        let factor_lanes = f64x4::splat(factor);
+        simd_unary_op(&mut values, |a| a * factor, |lanes| lanes * factor_lanes);
+        Self { values }
+    }
+
+    #[cfg(not(feature = "simd-rayon"))]
+    fn max(&self, other: &Self) -> Self {
+        Self {
+            values: self
+                .values
+                .iter()
+                .zip(&other.values)
+                .map(|(a, b)| a.max(*b))
+                .collect(),
+        }
+    }
+
+    #[cfg(feature = "simd-rayon")]
+    fn max(&self, other: &Self) -> Self {
+        let mut values = self.values.clone();
+        simd_binary_op(&mut values, &other.values, f64::max, f64x4::max);
+        Self { values }
+    }
+}
+
+impl std::ops::Index<usize> for Coeffs {
+    type Output = f64;
+
+    fn index(&self, i: usize) -> &f64 {
+        &self.values[i]
+    }
+}
+
+// Applies `lane_op` to whole `f64x4` lanes and `scalar_op` to the
+// (at most 3) trailing values that don't fill a lane, writing the result
+// back into `dst` in place.
+#[cfg(feature = "simd-rayon")]
+fn simd_binary_op(
+    dst: &mut [f64],
+    src: &[f64],
+    scalar_op: impl Fn(f64, f64) -> f64,
+    lane_op: impl Fn(f64x4, f64x4) -> f64x4,
+) {
+    let lanes = dst.len() / 4 * 4;
+    let mut i = 0;
+    while i < lanes {
+        let a = f64x4::from(&dst[i..i + 4]);
+        let b = f64x4::from(&src[i..i + 4]);
+        let r = lane_op(a, b).to_array();
+        dst[i..i + 4].copy_from_slice(&r);
+        i += 4;
+    }
+    for j in lanes..dst.len() {
+        dst[j] = scalar_op(dst[j], src[j]);
+    }
+}
+
+#[cfg(feature = "simd-rayon")]
+fn simd_unary_op(dst: &mut [f64], scalar_op: impl Fn(f64) -> f64, lane_op: impl Fn(f64x4) -> f64x4) {
+    let lanes = dst.len() / 4 * 4;
+    let mut i = 0;
+    while i < lanes {
+        let a = f64x4::from(&dst[i..i + 4]);
+        let r = lane_op(a).to_array();
+        dst[i..i + 4].copy_from_slice(&r);
+        i += 4;
+    }
+    for j in lanes..dst.len() {
+        dst[j] = scalar_op(dst[j]);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolicVariance {
+    pub coeffs: Coeffs,
+    pub partition: usize,
+}
+
+impl SymbolicVariance {
+    pub const ZERO: Self = Self {
+        coeffs: Coeffs { values: Vec::new() },
+        partition: usize::MAX,
+    };
+
+    pub fn nan(nb_partitions: usize) -> Self {
+        Self {
+            coeffs: Coeffs::nan(Self::nb_coeffs(nb_partitions)),
+            partition: usize::MAX,
+        }
+    }
+
+    pub fn input(nb_partitions: usize, partition: usize) -> Self {
+        let mut coeffs = Coeffs::zeroed(Self::nb_coeffs(nb_partitions));
+        coeffs.values[Self::input_index(nb_partitions, partition)] = 1.0;
+        Self { coeffs, partition }
+    }
+
+    pub fn after_pbs(nb_partitions: usize, partition: usize) -> Self {
+        let mut coeffs = Coeffs::zeroed(Self::nb_coeffs(nb_partitions));
+        coeffs.values[Self::pbs_index(nb_partitions, partition)] = 1.0;
+        Self { coeffs, partition }
+    }
+
+    #[must_use]
+    pub fn after_levelled_op(&self, manp: f64) -> Self {
+        Self {
+            coeffs: self.coeffs.mul_scalar(manp * manp),
+            partition: self.partition,
+        }
+    }
+
+    #[must_use]
+    pub fn after_partition_keyswitch_to_big(&self, src_partition: usize, dst_partition: usize) -> Self {
+        let nb_partitions = Self::nb_partitions_of(self.coeffs.values.len());
+        let mut coeffs = self.coeffs.clone();
+        coeffs.values[Self::fks_index(nb_partitions, src_partition, dst_partition)] += 1.0;
+        Self {
+            coeffs,
+            partition: dst_partition,
+        }
+    }
+
+    #[must_use]
+    pub fn max(&self, other: &Self) -> Self {
+        Self {
+            coeffs: self.coeffs.max(&other.coeffs),
+            partition: self.partition,
+        }
+    }
+
+    pub fn coeff_input(&self, partition: usize) -> f64 {
+        let nb_partitions = Self::nb_partitions_of(self.coeffs.values.len());
+        self.coeffs[Self::input_index(nb_partitions, partition)]
+    }
+
+    pub fn coeff_pbs(&self, partition: usize) -> f64 {
+        let nb_partitions = Self::nb_partitions_of(self.coeffs.values.len());
+        self.coeffs[Self::pbs_index(nb_partitions, partition)]
+    }
+
+    pub fn coeff_partition_keyswitch_to_big(&self, src_partition: usize, dst_partition: usize) -> f64 {
+        let nb_partitions = Self::nb_partitions_of(self.coeffs.values.len());
+        self.coeffs[Self::fks_index(nb_partitions, src_partition, dst_partition)]
+    }
+
+    // Layout: [input_0..input_n, pbs_0..pbs_n, fks_0_0..fks_n_n], i.e. one
+    // input coefficient and one pbs coefficient per partition, plus one fast
+    // keyswitch coefficient per (src, dst) partition pair.
+    fn nb_coeffs(nb_partitions: usize) -> usize {
+        2 * nb_partitions + nb_partitions * nb_partitions
+    }
+
+    fn nb_partitions_of(nb_coeffs: usize) -> usize {
+        // solves `2p + p^2 = nb_coeffs` for the positive root
+        (((1.0 + nb_coeffs as f64).sqrt()) - 1.0).round() as usize
+    }
+
+    fn input_index(nb_partitions: usize, partition: usize) -> usize {
+        let _ = nb_partitions;
+        partition
+    }
+
+    fn pbs_index(nb_partitions: usize, partition: usize) -> usize {
+        nb_partitions + partition
+    }
+
+    fn fks_index(nb_partitions: usize, src_partition: usize, dst_partition: usize) -> usize {
+        2 * nb_partitions + src_partition * nb_partitions + dst_partition
+    }
+}
+
+impl Add for SymbolicVariance {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for SymbolicVariance {
+    fn add_assign(&mut self, rhs: Self) {
+        // `SymbolicVariance::ZERO` has no coefficients at all (its length
+        // depends on `nb_partitions`, which it doesn't know); treat it as
+        // the additive identity rather than a same-length zero vector.
+        if self.coeffs.values.is_empty() {
+            *self = rhs;
+        } else if !rhs.coeffs.values.is_empty() {
+            self.coeffs.add_assign_scalar(&rhs.coeffs);
+        }
+    }
+}
+
+impl Mul<f64> for SymbolicVariance {
+    type Output = Self;
+
+    fn mul(self, weight: f64) -> Self {
+        Self {
+            coeffs: self.coeffs.mul_scalar(weight),
+            partition: self.partition,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_and_pbs_are_disjoint() {
+        let input = SymbolicVariance::input(3, 1);
+        let pbs = SymbolicVariance::after_pbs(3, 1);
+        assert!(input.coeff_input(1) == 1.0 && input.coeff_pbs(1) == 0.0);
+        assert!(pbs.coeff_pbs(1) == 1.0 && pbs.coeff_input(1) == 0.0);
+    }
+
+    #[test]
+    fn test_add_is_elementwise() {
+        let a = SymbolicVariance::input(2, 0);
+        let b = SymbolicVariance::after_pbs(2, 0);
+        let sum = a + b;
+        assert!(sum.coeff_input(0) == 1.0);
+        assert!(sum.coeff_pbs(0) == 1.0);
+    }
+
+    #[test]
+    fn test_after_levelled_op_scales_by_manp_squared() {
+        let a = SymbolicVariance::input(2, 0);
+        let scaled = a.after_levelled_op(2.0);
+        assert!(scaled.coeff_input(0) == 4.0);
+    }
+
+    #[test]
+    fn test_max_is_elementwise() {
+        let a = SymbolicVariance::input(2, 0) * 3.0;
+        let b = SymbolicVariance::input(2, 0) * 5.0;
+        let m = a.max(&b);
+        assert!(m.coeff_input(0) == 5.0);
+    }
+
+    #[test]
+    fn test_after_partition_keyswitch_to_big() {
+        let a = SymbolicVariance::after_pbs(2, 0);
+        let converted = a.after_partition_keyswitch_to_big(0, 1);
+        assert!(converted.coeff_partition_keyswitch_to_big(0, 1) == 1.0);
+        assert!(converted.partition == 1);
+    }
+}